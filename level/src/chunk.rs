@@ -1,8 +1,36 @@
-use std::{mem::MaybeUninit, ptr::NonNull};
+use std::{collections::TryReserveError, mem::MaybeUninit, ptr::NonNull};
 
+use bytemuck::Pod;
 use miners::encoding::{Decode, Encode};
 
+// `ByteArray<N>`/`HalfByteArray<N>` implement `bytemuck::Pod`/`Zeroable` in
+// `crate::containers` (they're `#[repr(transparent)]` over a plain byte array, so any
+// bit pattern is valid for them). That's what lets `split_field`/`take_field` below
+// reinterpret byte ranges of the section arena as typed fields via a checked cast
+// instead of raw pointer arithmetic.
 use crate::containers::{ByteArray, HalfByteArray};
+use crate::palette::{BiomePaletteContainer, StatePaletteContainer};
+use crate::pool::with_default_pool;
+
+/// Splits `size_of::<T>()` bytes off the front of `*buf` and reinterprets them as
+/// `&mut T` via `bytemuck`. This is the checked replacement for the old `update_ref`
+/// raw-pointer-offset helper: `bytemuck::from_bytes_mut` enforces the size (and, for
+/// anything wider than a byte, alignment) instead of relying on a prose safety comment.
+fn split_field<'a, T: Pod>(buf: &mut &'a mut [u8]) -> &'a mut T {
+    let taken = std::mem::take(buf);
+    let (field, rest) = taken.split_at_mut(std::mem::size_of::<T>());
+    *buf = rest;
+    bytemuck::from_bytes_mut(field)
+}
+
+/// Like [`split_field`], but also copies `src`'s value into the carved-out field. This
+/// is the checked replacement for the old `new_field` helper used by `from_reader` to
+/// copy a section field out of the decode buffer and into the column's own arena.
+fn take_field<'a, T: Pod>(buf: &mut &'a mut [u8], src: &T) -> &'a mut T {
+    let field = split_field(buf);
+    *field = *src;
+    field
+}
 
 #[inline]
 const fn bit_at(val: u16, idx: u8) -> bool {
@@ -10,83 +38,220 @@ const fn bit_at(val: u16, idx: u8) -> bool {
     (val >> idx) & 0b1 != 0
 }
 
-/// A chunk column, not including heightmaps
-pub struct ChunkColumn<const N: usize, S> {
-    pub sections: [Option<S>; N],
+/// A chunk column, not including heightmaps.
+///
+/// Sections are addressed by a signed section-Y coordinate rather than a flat array
+/// index, since 1.18+ worlds can extend below y=0 and configure their own height.
+/// Internally this is just `sections[section_y - min_section_y]`; see [`Self::index`].
+/// The v0 (pre-1.18) format, which is always exactly 16 sections starting at y=0, is the
+/// special case `ChunkColumn::new(0, 16)`.
+pub struct ChunkColumn<S> {
+    min_section_y: i32,
+    pub sections: Vec<Option<S>>,
+}
+
+impl<S> ChunkColumn<S> {
+    /// Creates an empty column spanning `count` sections starting at `min_section_y`
+    /// (inclusive).
+    pub fn new(min_section_y: i32, count: usize) -> Self {
+        let mut sections = Vec::new();
+        sections.resize_with(count, || None);
+        Self {
+            min_section_y,
+            sections,
+        }
+    }
+
+    /// The section-Y coordinate of `self.sections[0]`.
+    pub fn min_section_y(&self) -> i32 {
+        self.min_section_y
+    }
+
+    /// Translates a signed section-Y coordinate into an index into `sections`, or `None`
+    /// if it falls outside this column's height range.
+    fn index(&self, section_y: i32) -> Option<usize> {
+        let offset = section_y.checked_sub(self.min_section_y)?;
+        let index = usize::try_from(offset).ok()?;
+        (index < self.sections.len()).then_some(index)
+    }
+
+    /// Gets a reference to the section at `section_y`, if it's in range and present.
+    pub fn section(&self, section_y: i32) -> Option<&S> {
+        self.sections[self.index(section_y)?].as_ref()
+    }
+
+    /// Gets a mutable reference to the section at `section_y`, if it's in range and present.
+    pub fn section_mut(&mut self, section_y: i32) -> Option<&mut S> {
+        let index = self.index(section_y)?;
+        self.sections[index].as_mut()
+    }
+
+    /// Sets the section at `section_y`, returning `false` (and dropping `section`
+    /// instead of storing it) if `section_y` is outside this column's height range.
+    pub fn set_section(&mut self, section_y: i32, section: S) -> bool {
+        match self.index(section_y) {
+            Some(index) => {
+                self.sections[index] = Some(section);
+                true
+            }
+            None => false,
+        }
+    }
 }
 
+impl<S> ChunkColumn<S> {
+    /// Decodes a column of `count` sections starting at `min_section_y`, reading them
+    /// back-to-back with no presence bitmask, as in the 1.18+ chunk data format (every
+    /// section within a dimension's configured height range is always sent).
+    pub fn from_reader<'dec>(
+        cursor: &mut std::io::Cursor<&'dec [u8]>,
+        min_section_y: i32,
+        count: usize,
+    ) -> miners::encoding::decode::Result<Self>
+    where
+        S: Decode<'dec>,
+    {
+        let mut column = Self::new(min_section_y, count);
+        for slot in &mut column.sections {
+            *slot = Some(S::decode(cursor)?);
+        }
+        Ok(column)
+    }
+}
+
+/// A [`ChunkColumn0`]'s sections are addressed by a signed section-Y coordinate rather
+/// than a flat array index, the same as [`ChunkColumn`]; see that type's doc comment.
+/// The v0 format itself is always exactly 16 sections starting at y=0 (`min_section_y:
+/// 0`, `sections.len(): 16`), and `from_reader` always builds exactly that layout;
+/// `reallocate` works from `self.sections.len()` and `self.min_section_y` instead of
+/// hardcoding it. This carries its own `min_section_y` rather than reusing the generic
+/// [`ChunkColumn`] (which can't host the raw shared-arena buffer this type manages).
 pub struct ChunkColumn0<'a> {
     buf: Option<NonNull<u8>>,
     size: usize,
-    sections: [Option<ChunkSection0<'a>>; 16],
+    /// Capacity of the allocation backing `buf`. May exceed `size` when `buf` came from
+    /// a recycled [`crate::pool::ChunkColumnPool`] block of a larger size class than strictly needed.
+    capacity: usize,
+    min_section_y: i32,
+    sections: Vec<Option<ChunkSection0<'a>>>,
+    /// One entry per [`Self::UNDEF_BLOCK`]-byte block of `buf`; `true` means that block
+    /// has never been written to. Only tracked behind the `undef-tracking` feature so
+    /// release builds pay nothing for it.
+    #[cfg(feature = "undef-tracking")]
+    undef: Vec<bool>,
 }
 
 impl ChunkColumn0<'_> {
     const MINIMUM_SECTION_SIZE: usize = 4096 + (3 * 2048);
-
-    /// Constructs a new `ChunkColumn0`, doesn't allocate.
-    pub fn new() -> Self {
+    /// Granularity of the undef mask: the smallest field in a section (`metadata`,
+    /// `light`, `sky_light`, `add`, `biomes`) is 2048 bytes, and `blocks` is an exact
+    /// multiple of it, so tracking per-block is precise enough to catch a field read
+    /// that overlaps uninitialized data without tracking per byte.
+    #[cfg(feature = "undef-tracking")]
+    const UNDEF_BLOCK: usize = 2048;
+
+    /// Constructs a new, empty column spanning `count` sections starting at
+    /// `min_section_y` (inclusive). Doesn't allocate.
+    pub fn with_range(min_section_y: i32, count: usize) -> Self {
+        let mut sections = Vec::new();
+        sections.resize_with(count, || None);
         Self {
             buf: None,
             size: 0,
-            sections: [
-                None, None, None, None, None, None, None, None, None, None, None, None, None, None,
-                None, None,
-            ],
+            capacity: 0,
+            min_section_y,
+            sections,
+            #[cfg(feature = "undef-tracking")]
+            undef: Vec::new(),
         }
     }
 
+    /// Constructs a new v0-format `ChunkColumn0` (16 sections starting at y=0), doesn't
+    /// allocate.
+    pub fn new() -> Self {
+        Self::with_range(0, 16)
+    }
+
+    /// The section-Y coordinate of `self.sections[0]`.
+    pub fn min_section_y(&self) -> i32 {
+        self.min_section_y
+    }
+
+    /// Translates a signed section-Y coordinate into an index into `self.sections`, or
+    /// `None` if it falls outside this column's height range. See
+    /// [`ChunkColumn::index`], which this mirrors.
+    fn index(&self, section_y: i32) -> Option<usize> {
+        let offset = section_y.checked_sub(self.min_section_y)?;
+        let index = usize::try_from(offset).ok()?;
+        (index < self.sections.len()).then_some(index)
+    }
+
+    /// Takes apart a column into its raw buffer and capacity, taking `buf` out first so
+    /// the column's `Drop` frees nothing and the caller (the
+    /// [`crate::pool::ChunkColumnPool`]) takes over ownership of the allocation.
+    pub(crate) fn into_raw_parts(mut self) -> (Option<NonNull<u8>>, usize) {
+        let buf = self.buf.take();
+        let capacity = self.capacity;
+        (buf, capacity)
+    }
+
     /// Reallocates the internal buffer extending it with `N` and returning a reference to the part of the buffer that was just added.
     pub fn reallocate<'a, const N: usize>(&'a mut self) -> &'a mut [MaybeUninit<u8>; N] {
         assert!(N != 0);
-        
-        let mut vec = Vec::<u8>::with_capacity(self.size + N);
-        let new = vec.as_mut_ptr();
-        std::mem::forget(vec);
 
-        let mut sections: [Option<ChunkSection0>; 16] = [
-            None, None, None, None, None, None, None, None, None, None, None, None, None, None,
-            None, None,
-        ];
+        let needed = self.size + N;
+        let reused_in_place = needed <= self.capacity;
+        let (new, new_capacity) = if reused_in_place {
+            // The existing allocation already has room; no need to touch the pool.
+            (
+                self.buf.expect("capacity > 0 implies an allocated buffer"),
+                self.capacity,
+            )
+        } else {
+            with_default_pool(|pool| pool.take(needed))
+        };
+        let new = new.as_ptr();
+
+        let mut sections: Vec<Option<ChunkSection0>> = Vec::new();
+        sections.resize_with(self.sections.len(), || None);
 
         if let Some(buf) = self.buf {
-            // SAFETY: This is fine because we know self.buf is initialised and new and self.buf don't overlap.
-            unsafe { std::ptr::copy_nonoverlapping(buf.as_ptr(), new, self.size) };
-            let mut p = new;
-
-            /// # Safety
-            /// dst should be allocated properly, initialised, and no other references should point to it
-            unsafe fn update_ref<'a, const N: usize, T: From<&'a mut [u8; N]>>(
-                dst: &mut *mut u8,
-            ) -> T {
-                let p = dst.cast() as *mut [u8; N];
-                *dst = dst.add(N);
-                (&mut *p).into()
+            // Read out which optional fields each old section carries, and then drop
+            // `self.sections` entirely, *before* building any new references into `new`.
+            // In the `reused_in_place` case `new == buf`, so the old sections' `&mut`
+            // references and the new ones built below alias the same bytes; holding both
+            // live at once would be two overlapping mutable references into the same
+            // memory. Taking `self.sections` (replacing it with all-`None`) ends the old
+            // references here, before `remaining` ever exists, so the two never overlap.
+            let old_sections: Vec<Option<(bool, bool)>> = self
+                .sections
+                .iter()
+                .map(|s| s.as_ref().map(|s| (s.sky_light.is_some(), s.add.is_some())))
+                .collect();
+            self.sections.iter_mut().for_each(|s| *s = None);
+
+            if !reused_in_place {
+                // SAFETY: This is fine because we know self.buf is initialised and new and self.buf don't overlap.
+                unsafe { std::ptr::copy_nonoverlapping(buf.as_ptr(), new, self.size) };
+                // The old allocation is no longer referenced by anything; hand it back
+                // to the pool instead of leaking it (it isn't freed by `Drop` here since
+                // `self` is about to be overwritten wholesale below).
+                with_default_pool(|pool| pool.recycle_raw(buf, self.capacity));
             }
-            for i in 0..16 {
-                if let Some(old_section) = &self.sections[i] {
+            // SAFETY: `new` was just allocated (or is being reused in place) with at
+            // least `new_capacity` initialized-or-not bytes. `self.sections` was just
+            // cleared above, so no other reference into it is still live.
+            let mut remaining: &mut [u8] =
+                unsafe { std::slice::from_raw_parts_mut(new, new_capacity) };
+            for (i, old_section) in old_sections.into_iter().enumerate() {
+                if let Some((has_sky_light, has_add)) = old_section {
                     let section = Some(ChunkSection0 {
-                        // SAFETY: We know dst is allocated, initialised and no other references point to it so this is fine.
-                        blocks: unsafe { update_ref(&mut p) },
-                        // SAFETY: See safety comment for `blocks`.
-                        metadata: unsafe { update_ref(&mut p) },
-                        // SAFETY: See safety comment for `blocks`.
-                        light: unsafe { update_ref(&mut p) },
-                        // SAFETY: See safety comment for `blocks`.
-                        sky_light: if old_section.sky_light.is_some() {
-                            // SAFETY: See safety comment for `blocks`.
-                            Some(unsafe { update_ref(&mut p) })
-                        } else {
-                            None
-                        },
-                        add: if old_section.add.is_some() {
-                            // SAFETY: See safety comment for `blocks`.
-                            Some(unsafe { update_ref(&mut p) })
-                        } else {
-                            None
-                        },
-                        // SAFETY: See safety comment for `blocks`.
-                        biomes: unsafe { update_ref(&mut p) },
+                        blocks: split_field(&mut remaining),
+                        metadata: split_field(&mut remaining),
+                        light: split_field(&mut remaining),
+                        sky_light: has_sky_light.then(|| split_field(&mut remaining)),
+                        add: has_add.then(|| split_field(&mut remaining)),
+                        biomes: split_field(&mut remaining),
                     });
                     sections[i] = section;
                 }
@@ -96,10 +261,27 @@ impl ChunkColumn0<'_> {
             // SAFETY: This is safe because we know new isn't a null pointer.
             buf: unsafe { Some(NonNull::new_unchecked(new)) },
             size: self.size + N,
+            capacity: new_capacity,
+            min_section_y: self.min_section_y,
             sections,
+            // The existing blocks were already initialized (or weren't, and stay
+            // that way); the newly extended tail starts out uninitialized.
+            #[cfg(feature = "undef-tracking")]
+            undef: {
+                let mut undef = std::mem::take(&mut self.undef);
+                undef.resize((self.size + N).div_ceil(Self::UNDEF_BLOCK), true);
+                undef
+            },
         };
 
         let old_size = self.size;
+        // `*self = this` below runs `self`'s current value through `ChunkColumn0::drop`
+        // before moving `this` in, same as any other assignment to a place whose type
+        // has a `Drop` impl. Null out `self.buf` first so that implicit drop is a no-op:
+        // in the `!reused_in_place` branch `buf` was already handed back to the pool
+        // above, and in the `reused_in_place` branch `new` (now owned by `this`) is the
+        // very same pointer — either way, freeing it here would double-free it.
+        self.buf = None;
         *self = this;
 
         // SAFETY: This is to return a reference to the (uninitialised) added part of the buffer
@@ -107,31 +289,105 @@ impl ChunkColumn0<'_> {
     }
 }
 
-impl<'a> ChunkColumn0<'a> {
-    /// Gets a reference to the section if it exists.
-    pub fn section(&self, section: usize) -> Option<&ChunkSection0<'a>> {
-        if let Some(ref section) = self.sections[section] {
-            Some(section)
-        } else {
-            None
+#[cfg(feature = "undef-tracking")]
+impl ChunkColumn0<'_> {
+    /// Byte offset of `ptr` within `buf`.
+    fn offset_of(&self, ptr: *const u8) -> usize {
+        let base = self
+            .buf
+            .expect("buf must be allocated to hold a field pointer")
+            .as_ptr() as usize;
+        ptr as usize - base
+    }
+
+    /// Panics if any byte in `[offset, offset + len)` is still flagged uninitialized.
+    fn assert_field_initialized(&self, ptr: *const u8, len: usize) {
+        let offset = self.offset_of(ptr);
+        let blocks = (offset / Self::UNDEF_BLOCK)..(offset + len).div_ceil(Self::UNDEF_BLOCK);
+        for block in blocks {
+            debug_assert!(
+                !self.undef.get(block).copied().unwrap_or(true),
+                "read of a ChunkSection0 field overlapping uninitialized column data \
+                 at byte {offset} (block {block})"
+            );
         }
     }
 
-    /// Gets a mutable reference to the section if it exists.
-    pub fn section_mut(&mut self, section: usize) -> Option<&mut ChunkSection0<'a>> {
-        if let Some(ref mut section) = self.sections[section] {
-            Some(section)
-        } else {
-            None
+    /// Debug-only check that every field of `section` lies within initialized bytes.
+    fn assert_section_initialized(&self, section: &ChunkSection0<'_>) {
+        // SAFETY: `ByteArray`/`HalfByteArray` are `#[repr(transparent)]` byte arrays, so
+        // reinterpreting the reference as a byte pointer for offset purposes is sound.
+        self.assert_field_initialized((section.blocks as *const _ as *const u8), 4096);
+        self.assert_field_initialized((section.metadata as *const _ as *const u8), 2048);
+        self.assert_field_initialized((section.light as *const _ as *const u8), 2048);
+        if let Some(sky_light) = &section.sky_light {
+            self.assert_field_initialized((*sky_light as *const _ as *const u8), 2048);
+        }
+        if let Some(add) = &section.add {
+            self.assert_field_initialized((*add as *const _ as *const u8), 2048);
         }
+        self.assert_field_initialized((section.biomes as *const _ as *const u8), 2048);
+    }
+
+    /// Marks the `len` bytes starting at `ptr` (which must point within `buf`) as
+    /// initialized. The write-side counterpart to `assert_field_initialized`: callers
+    /// that write new section data into the tail `reallocate` just returned must call
+    /// this (directly, or via `mark_section_initialized` for a whole section) before the
+    /// next `section`/`section_mut` read of that data, or `assert_field_initialized`
+    /// will permanently (and correctly, absent this call) flag it as uninitialized.
+    pub fn mark_initialized(&mut self, ptr: *const u8, len: usize) {
+        let offset = self.offset_of(ptr);
+        let blocks = (offset / Self::UNDEF_BLOCK)..(offset + len).div_ceil(Self::UNDEF_BLOCK);
+        for block in blocks {
+            if let Some(flag) = self.undef.get_mut(block) {
+                *flag = false;
+            }
+        }
+    }
+
+    /// Marks every field of `section` as initialized in one call. See [`Self::mark_initialized`].
+    pub fn mark_section_initialized(&mut self, section: &ChunkSection0<'_>) {
+        // SAFETY: see `assert_section_initialized`.
+        self.mark_initialized((section.blocks as *const _ as *const u8), 4096);
+        self.mark_initialized((section.metadata as *const _ as *const u8), 2048);
+        self.mark_initialized((section.light as *const _ as *const u8), 2048);
+        if let Some(sky_light) = &section.sky_light {
+            self.mark_initialized((*sky_light as *const _ as *const u8), 2048);
+        }
+        if let Some(add) = &section.add {
+            self.mark_initialized((*add as *const _ as *const u8), 2048);
+        }
+        self.mark_initialized((section.biomes as *const _ as *const u8), 2048);
+    }
+}
+
+impl<'a> ChunkColumn0<'a> {
+    /// Gets a reference to the section at `section_y`, if it's in range and present.
+    pub fn section(&self, section_y: i32) -> Option<&ChunkSection0<'a>> {
+        let section = self.sections[self.index(section_y)?].as_ref()?;
+        #[cfg(feature = "undef-tracking")]
+        self.assert_section_initialized(section);
+        Some(section)
+    }
+
+    /// Gets a mutable reference to the section at `section_y`, if it's in range and present.
+    pub fn section_mut(&mut self, section_y: i32) -> Option<&mut ChunkSection0<'a>> {
+        let index = self.index(section_y)?;
+        #[cfg(feature = "undef-tracking")]
+        if let Some(section) = &self.sections[index] {
+            self.assert_section_initialized(section);
+        }
+        self.sections[index].as_mut()
     }
 }
 
 impl<'a> Drop for ChunkColumn0<'a> {
     fn drop(&mut self) {
-        // SAFETY: This is fine because the buffer was allocated with `Vec`.
+        // SAFETY: This is fine because the buffer was allocated with `Vec`, `self.size` is
+        // always within `self.capacity`, and columns handed to a pool via
+        // `into_raw_parts`/`recycle` have `self.buf` taken first so this doesn't double-free.
         if let Some(buf) = self.buf {
-            let vec = unsafe { Vec::<u8>::from_raw_parts(buf.as_ptr(), self.size, self.size) };
+            let vec = unsafe { Vec::<u8>::from_raw_parts(buf.as_ptr(), self.size, self.capacity) };
             drop(vec)
         }
     }
@@ -167,70 +423,44 @@ impl<'a> ChunkColumn0<'a> {
             }
         }
         let size = (nsections * Self::MINIMUM_SECTION_SIZE) + (nsky_light * 2048) + (nadd * 2048);
-        let mut vec = Vec::<u8>::with_capacity(size);
-        let data = vec.as_mut_ptr();
-        std::mem::forget(vec);
+        // Pull the backing buffer from the thread-local pool instead of allocating fresh,
+        // so decoding a stream of columns (and recycling each one back when done) doesn't
+        // thrash the allocator with a `Vec::with_capacity` + free per column.
+        let (data, capacity) = with_default_pool(|pool| pool.take(size));
+        let data = data.as_ptr();
 
-        let mut sections: [Option<ChunkSection0>; 16] = [
-            None, None, None, None, None, None, None, None, None, None, None, None, None, None,
-            None, None,
-        ];
+        let mut sections: Vec<Option<ChunkSection0>> = Vec::new();
+        sections.resize_with(16, || None);
 
         // loop through the sections
-        let mut p = data;
+        // SAFETY: `data` was just allocated with at least `size` bytes and nothing else
+        // holds a reference into it yet.
+        let mut remaining: &mut [u8] = unsafe { std::slice::from_raw_parts_mut(data, size) };
         for i in 0u8..16 {
             if let Some(section) = decode_sections[i as usize] {
-                #[inline]
-                // TODO: come up with a better name
-                /// # Safety
-                /// dst should be allocated properly and no other references should point to it
-                unsafe fn new_field<'a, const N: usize, T: Into<&'a [u8; N]>>(
-                    dst: &mut *mut u8,
-                    src: T,
-                ) -> &'a mut [u8; N] {
-                    let p = dst.cast() as *mut [u8; N];
-                    p.copy_from_nonoverlapping(Into::<&[u8; N]>::into(src), 1);
-                    *dst = dst.add(N);
-                    &mut *p
-                }
-
                 let section = ChunkSection0 {
-                    // SAFETY: This is fine because we know dst (p) was properly allocated and there are no references to it.
-                    // (a pointer is not a reference)
-                    blocks: unsafe { (new_field(&mut p, section.blocks)).into() },
-                    // SAFETY: See safety comment for `blocks`
-                    metadata: unsafe { (new_field(&mut p, section.metadata)).into() },
-                    // SAFETY: See safety comment for `blocks`
-                    light: unsafe { (new_field(&mut p, section.light)).into() },
-                    sky_light: if let Some(v) = section.sky_light {
-                        Some(
-                            // SAFETY: See safety comment for `blocks`
-                            unsafe { (new_field(&mut p, v)).into() },
-                        )
-                    } else {
-                        None
-                    },
-                    add: if let Some(v) = section.add {
-                        Some(
-                            // SAFETY: See safety comment for `blocks`
-                            unsafe { (new_field(&mut p, v)).into() },
-                        )
-                    } else {
-                        None
-                    },
-                    // SAFETY: See safety comment for `blocks`
-                    biomes: unsafe { (new_field(&mut p, section.biomes)).into() },
+                    blocks: take_field(&mut remaining, section.blocks),
+                    metadata: take_field(&mut remaining, section.metadata),
+                    light: take_field(&mut remaining, section.light),
+                    sky_light: section.sky_light.map(|v| take_field(&mut remaining, v)),
+                    add: section.add.map(|v| take_field(&mut remaining, v)),
+                    biomes: take_field(&mut remaining, section.biomes),
                 };
                 sections[i as usize] = Some(section);
             }
         }
-        // SAFETY: This is fine because ChunkSection0 and ChunkSection0Decode have the same type layout
         Ok(Self {
             // SAFETY: This is fine because we know data is not null
             buf: unsafe { Some(NonNull::new_unchecked(data)) },
             size,
-            // SAFETY: This is fine because we know both union fields have the exact same layout.
+            capacity,
+            // The v0 format is always exactly 16 sections starting at y=0.
+            min_section_y: 0,
             sections,
+            // Every byte of `data` was just copied from the decoded fields above, so the
+            // whole buffer is initialized.
+            #[cfg(feature = "undef-tracking")]
+            undef: vec![false; size.div_ceil(Self::UNDEF_BLOCK)],
         })
     }
 }
@@ -289,6 +519,28 @@ pub struct ChunkSection<S, B> {
     pub biomes: B,
 }
 
+impl<const N: usize, B> ChunkSection<StatePaletteContainer<N>, B> {
+    /// The block state ID universally reserved for air.
+    const AIR: u64 = 0;
+
+    /// Sets the block at `i` to `v`, keeping `block_count` in sync with the number of
+    /// non-air blocks in `self.states` as it's written to directly by this call.
+    pub fn set_block(&mut self, i: usize, v: u64) {
+        self.try_set_block(i, v).expect("allocation failure")
+    }
+
+    /// Fallible counterpart to [`Self::set_block`].
+    pub fn try_set_block(&mut self, i: usize, v: u64) -> Result<(), TryReserveError> {
+        let old = self.states.try_swap(i, v)?;
+        match (old == Self::AIR, v == Self::AIR) {
+            (true, false) => self.block_count += 1,
+            (false, true) => self.block_count -= 1,
+            _ => {}
+        }
+        Ok(())
+    }
+}
+
 impl<S: Encode, B: Encode> Encode for ChunkSection<S, B> {
     fn encode(&self, writer: &mut impl std::io::Write) -> miners::encoding::encode::Result<()> {
         self.block_count.encode(writer)?;
@@ -307,9 +559,68 @@ impl<S: for<'a> Decode<'a>, B: for<'a> Decode<'a>> Decode<'_> for ChunkSection<S
     }
 }
 
+/// A [`ChunkSection`] using the modern (1.9+) global-palette block/biome containers:
+/// a `SingleValue`/`Linear`/`Mapped`/`Global` block-state palette over the section's
+/// 4096 block positions, and the same scheme clamped to 3 bits over its 64 biome cells.
+pub type PalettedChunkSection = ChunkSection<StatePaletteContainer<4096>, BiomePaletteContainer<64>>;
+
 #[cfg(test)]
 mod tests {
-    use super::{bit_at, ChunkColumn0};
+    use super::{bit_at, ChunkColumn, ChunkColumn0};
+
+    #[test]
+    #[cfg(feature = "undef-tracking")]
+    fn mark_initialized_clears_the_undef_flag() {
+        let mut column = ChunkColumn0::new();
+        let buf = column.reallocate::<2048>();
+        let ptr = buf.as_mut_ptr().cast::<u8>();
+        for byte in buf.iter_mut() {
+            byte.write(0);
+        }
+        // `buf` isn't used again after this point, so its borrow of `column` ends here.
+
+        let block = column.offset_of(ptr) / ChunkColumn0::UNDEF_BLOCK;
+        assert!(column.undef[block], "freshly extended tail starts uninitialized");
+
+        column.mark_initialized(ptr, 2048);
+        assert!(
+            !column.undef[block],
+            "mark_initialized should have cleared the block covering the written bytes"
+        );
+    }
+
+    #[test]
+    fn chunk_column_signed_section_y() {
+        // A tall-world-shaped column: 8 sections starting at y=-4, i.e. covering -4..=3.
+        let mut column: ChunkColumn<i32> = ChunkColumn::new(-4, 8);
+        assert_eq!(column.min_section_y(), -4);
+        assert!(column.section(-5).is_none(), "below range");
+        assert!(column.section(4).is_none(), "above range");
+        assert!(column.section(-4).is_none(), "not yet set");
+
+        assert!(column.set_section(-4, 100));
+        assert!(column.set_section(3, 200));
+        assert!(!column.set_section(4, 300), "out of range, should be rejected");
+
+        assert_eq!(column.section(-4), Some(&100));
+        assert_eq!(column.section(3), Some(&200));
+        *column.section_mut(-4).unwrap() = 111;
+        assert_eq!(column.section(-4), Some(&111));
+    }
+
+    #[test]
+    fn chunk_column0_signed_range() {
+        // The v0 path is the special case of this with min=0, count=16.
+        let v0 = ChunkColumn0::new();
+        assert_eq!(v0.min_section_y(), 0);
+
+        // A tall-world-shaped v0 column spanning negative section-Y coordinates.
+        let column = ChunkColumn0::with_range(-4, 24);
+        assert_eq!(column.min_section_y(), -4);
+        assert!(column.section(-4).is_none(), "empty column has no sections yet");
+        assert!(column.section(-5).is_none(), "below range");
+        assert!(column.section(20).is_none(), "above range");
+    }
 
     #[test]
     fn t_bit_at() {
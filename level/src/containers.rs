@@ -0,0 +1,55 @@
+use bytemuck::{Pod, Zeroable};
+use miners::encoding::{decode, Decode};
+
+/// A fixed-size, densely-packed byte array: one full byte per entry.
+///
+/// `#[repr(transparent)]` over `[u8; N]`, so every bit pattern is a valid value and it
+/// can be reinterpreted from/into a raw byte slice with no validation, which is what
+/// backs both its [`bytemuck::Pod`]/[`bytemuck::Zeroable`] impls below and the
+/// zero-copy [`Decode`] impl (borrowing `N` bytes directly out of the decode cursor
+/// rather than copying them).
+#[repr(transparent)]
+#[derive(Clone, Copy)]
+pub struct ByteArray<const N: usize>(pub [u8; N]);
+
+/// A fixed-size, half-byte-packed array: two 4-bit entries per byte, `N` bytes holding
+/// `2 * N` entries. Used for the per-block metadata/light/sky_light/add/biomes fields,
+/// which only ever need a nibble of range. Same `#[repr(transparent)]`/zero-copy
+/// rationale as [`ByteArray`].
+#[repr(transparent)]
+#[derive(Clone, Copy)]
+pub struct HalfByteArray<const N: usize>(pub [u8; N]);
+
+// SAFETY: `ByteArray<N>`/`HalfByteArray<N>` are `#[repr(transparent)]` over `[u8; N]`,
+// which is `Pod`/`Zeroable` for any `N`, any bit pattern included.
+unsafe impl<const N: usize> Zeroable for ByteArray<N> {}
+// SAFETY: see above.
+unsafe impl<const N: usize> Pod for ByteArray<N> {}
+// SAFETY: see above.
+unsafe impl<const N: usize> Zeroable for HalfByteArray<N> {}
+// SAFETY: see above.
+unsafe impl<const N: usize> Pod for HalfByteArray<N> {}
+
+impl<'dec, const N: usize> Decode<'dec> for &'dec ByteArray<N> {
+    fn decode(cursor: &mut std::io::Cursor<&'dec [u8]>) -> decode::Result<Self> {
+        decode_borrowed(cursor)
+    }
+}
+
+impl<'dec, const N: usize> Decode<'dec> for &'dec HalfByteArray<N> {
+    fn decode(cursor: &mut std::io::Cursor<&'dec [u8]>) -> decode::Result<Self> {
+        decode_borrowed(cursor).map(bytemuck::cast_ref)
+    }
+}
+
+/// Borrows the next `N` bytes of `cursor` as a `&'dec ByteArray<N>` with no copy,
+/// advancing the cursor's position past them. `read_exact` is just used to get the
+/// usual `io::Error` -> [`decode::Error`] bounds-checking for free; the actual value
+/// returned is a reference into `cursor`'s own backing slice, not the scratch buffer.
+fn decode_borrowed<'dec, const N: usize>(
+    cursor: &mut std::io::Cursor<&'dec [u8]>,
+) -> decode::Result<&'dec ByteArray<N>> {
+    let pos = cursor.position() as usize;
+    std::io::Read::read_exact(cursor, &mut [0u8; N])?;
+    Ok(bytemuck::from_bytes(&cursor.get_ref()[pos..pos + N]))
+}
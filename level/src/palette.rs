@@ -1,8 +1,22 @@
 use crate::bitpack::PackedBits;
-use std::collections::BTreeMap;
+use miners::encoding::{
+    types::{LenPrefixed, VarInt},
+    Decode, Encode,
+};
+use std::collections::{BTreeMap, TryReserveError};
 
 // TODO: Reduce code duplication (with macros?)
 
+/// Checks an `unsafe fn`'s documented precondition in debug/test builds, panicking with
+/// a clear message if it's violated. Compiled out entirely in release builds (this is
+/// exactly what `debug_assert!` already does), preserving the zero-cost contract of the
+/// surrounding `unsafe fn` while giving fuzzing and unit tests something to catch.
+macro_rules! precondition {
+    ($cond:expr, $($msg:tt)+) => {
+        debug_assert!($cond, $($msg)+)
+    };
+}
+
 pub struct BiomePaletteContainer<const N: usize> {
     palette: BiomePalette<N>,
 }
@@ -34,13 +48,36 @@ impl<const N: usize> BiomePaletteContainer<N> {
     /// # Safety
     /// This method is safe as long as `bits` is not greater than 3.
     pub unsafe fn with_bits_unchecked(bits: usize, value: u64) -> Self {
-        match bits {
+        Self::try_with_bits_unchecked(bits, value).expect("allocation failure")
+    }
+
+    /// Fallible counterpart to [`Self::with_bits_unchecked`].
+    ///
+    /// # Safety
+    /// This method is safe as long as `bits` is not greater than 3.
+    pub unsafe fn try_with_bits_unchecked(
+        bits: usize,
+        value: u64,
+    ) -> Result<Self, TryReserveError> {
+        precondition!(bits <= 3, "bits ({bits}) must not exceed 3");
+        Ok(match bits {
             0 => Self::new(value),
             // Here we assume bits is 1, 2, or 3
             bits => {
                 let mut values = Vec::new();
-                values.reserve_exact(2usize.pow(bits as u32));
-                let palette = LinearPalette { bits, values };
+                values.try_reserve_exact(2usize.pow(bits as u32))?;
+                values.push(value);
+                // `data` is freshly zeroed, so every cell's raw index is 0 until a
+                // caller's first `set()` overwrites it; seeding `values`/`counts` as if
+                // all N cells already held `value` at index 0 keeps that first `set()`
+                // (which reads the stale `old = 0` and calls `release(0)`) from
+                // double-subtracting an entry that was never really there.
+                let counts = vec![N as u32];
+                let palette = LinearPalette {
+                    bits,
+                    values,
+                    counts,
+                };
                 Self {
                     palette: BiomePalette::Linear {
                         palette,
@@ -48,12 +85,12 @@ impl<const N: usize> BiomePaletteContainer<N> {
                     },
                 }
             }
-        }
+        })
     }
 }
 
 impl<const N: usize> BiomePaletteContainer<N> {
-    pub fn get(&mut self, i: usize) -> u64 {
+    pub fn get(&self, i: usize) -> u64 {
         if i >= N {
             panic!("out of bounds")
         }
@@ -63,80 +100,244 @@ impl<const N: usize> BiomePaletteContainer<N> {
 
     /// # Safety
     /// This method is safe as long as `i` is within bounds.
-    pub unsafe fn get_unchecked(&mut self, i: usize) -> u64 {
-        match &mut self.palette {
+    pub unsafe fn get_unchecked(&self, i: usize) -> u64 {
+        precondition!(i < N, "i ({i}) must be < N ({N})");
+        match &self.palette {
             BiomePalette::SingleValue(v) => v.0,
             BiomePalette::Linear { palette, data } => palette.value(data.get_unchecked(i) as usize),
         }
     }
 
     pub fn set(&mut self, i: usize, v: u64) {
+        self.try_set(i, v).expect("allocation failure")
+    }
+
+    /// Fallible counterpart to [`Self::set`] that rejects the write instead of aborting
+    /// the process when growing the backing storage fails, e.g. because an attacker-
+    /// controlled chunk tries to force the palette up to an unreasonable size.
+    pub fn try_set(&mut self, i: usize, v: u64) -> Result<(), TryReserveError> {
         if i >= N {
             panic!("out of bounds")
         }
         // SAFETY: This is sound because we just checked the bounds
-        unsafe { self.set_unchecked(i, v) }
+        unsafe { self.try_set_unchecked(i, v) }
     }
 
     /// # Safety
     /// This method is safe as long as `i` is within bounds.
     pub unsafe fn set_unchecked(&mut self, i: usize, v: u64) {
+        self.try_set_unchecked(i, v).expect("allocation failure")
+    }
+
+    /// Fallible counterpart to [`Self::set_unchecked`].
+    ///
+    /// # Safety
+    /// This method is safe as long as `i` is within bounds.
+    pub unsafe fn try_set_unchecked(&mut self, i: usize, v: u64) -> Result<(), TryReserveError> {
+        precondition!(i < N, "i ({i}) must be < N ({N})");
         loop {
             match &mut self.palette {
                 BiomePalette::SingleValue(val) => match val.index(v) {
-                    IndexOrBits::Index(_) => return,
+                    IndexOrBits::Index(_) => return Ok(()),
                     IndexOrBits::Bits(bits) => {
                         let mut values = Vec::new();
-                        values.reserve_exact(2);
+                        values.try_reserve_exact(2)?;
                         values.push(val.0);
+                        // All N cells are still implicitly holding `val.0` at this point;
+                        // the loop re-enters the `Linear` arm below for the same `i`,
+                        // which writes its new index and calls `palette.release(old)`
+                        // (reading the fresh, all-zero `data` as `old == 0`) to drop this
+                        // count by one. Seeding it to `N` rather than `N - 1` accounts for
+                        // that still-pending release instead of double-subtracting `i`.
+                        let counts = vec![N as u32];
                         let palette = BiomePalette::Linear {
-                            palette: LinearPalette { bits, values },
+                            palette: LinearPalette {
+                                bits,
+                                values,
+                                counts,
+                            },
                             data: PackedBits::new(1),
                         };
                         self.palette = palette
                     }
                 },
-                BiomePalette::Linear { palette, data } => match palette.index(v) {
-                    IndexOrBits::Index(v) => return data.set_unchecked(i, v),
-                    IndexOrBits::Bits(bits) => {
-                        if bits > 3 {
-                            panic!("bits cannot exceed 3")
+                BiomePalette::Linear { palette, data } => {
+                    let old = data.get_unchecked(i);
+                    match palette.try_index(v)? {
+                        IndexOrBits::Index(new_index) => {
+                            data.set_unchecked(i, new_index);
+                            palette.release(old as usize);
+                            return Ok(());
                         }
-                        let mut values = std::mem::take(&mut palette.values);
-                        values.reserve_exact(values.capacity());
-                        data.change_bits(bits);
+                        IndexOrBits::Bits(bits) => {
+                            if bits > 3 {
+                                panic!("bits cannot exceed 3")
+                            }
+                            let mut values = std::mem::take(&mut palette.values);
+                            let counts = std::mem::take(&mut palette.counts);
+                            values.try_reserve_exact(values.capacity())?;
+                            data.try_change_bits(bits)?;
 
-                        let data = std::mem::take(data);
+                            let data = std::mem::take(data);
 
-                        let palette = BiomePalette::Linear {
-                            palette: LinearPalette { bits, values },
-                            data,
-                        };
+                            let palette = BiomePalette::Linear {
+                                palette: LinearPalette {
+                                    bits,
+                                    values,
+                                    counts,
+                                },
+                                data,
+                            };
 
-                        self.palette = palette
+                            self.palette = palette
+                        }
                     }
-                },
+                }
             }
         }
     }
 
     pub fn swap(&mut self, i: usize, v: u64) -> u64 {
+        self.try_swap(i, v).expect("allocation failure")
+    }
+
+    /// Fallible counterpart to [`Self::swap`].
+    pub fn try_swap(&mut self, i: usize, v: u64) -> Result<u64, TryReserveError> {
         if i >= N {
             panic!("out of bounds")
         }
         //SAFETY: This is safe because we just checked the bounds.
-        unsafe { self.swap_unchecked(i, v) }
+        unsafe { self.try_swap_unchecked(i, v) }
     }
 
     /// # Safety
     /// This method is safe as long as `i` is within bounds
     pub unsafe fn swap_unchecked(&mut self, i: usize, v: u64) -> u64 {
+        self.try_swap_unchecked(i, v).expect("allocation failure")
+    }
+
+    /// Fallible counterpart to [`Self::swap_unchecked`].
+    ///
+    /// # Safety
+    /// This method is safe as long as `i` is within bounds
+    pub unsafe fn try_swap_unchecked(
+        &mut self,
+        i: usize,
+        v: u64,
+    ) -> Result<u64, TryReserveError> {
         let val = self.get_unchecked(i);
-        self.set_unchecked(i, v);
-        val
+        self.try_set_unchecked(i, v)?;
+        Ok(val)
+    }
+
+    /// Recomputes the minimal palette tier needed to represent the current contents
+    /// and repacks the data accordingly, downgrading the tier if writes have since
+    /// left only a handful of distinct values live. `get(i)` returns the same values
+    /// before and after this call.
+    pub fn optimize(&mut self) {
+        let mut distinct: Vec<u64> = Vec::new();
+        for i in 0..N {
+            // SAFETY: i is in bounds due to the loop bound.
+            let v = unsafe { self.get_unchecked(i) };
+            if !distinct.contains(&v) {
+                distinct.push(v);
+            }
+        }
+
+        let mut optimized = match distinct.len() {
+            0 | 1 => Self::new(distinct.first().copied().unwrap_or(0)),
+            n => Self::with_bits(ceil_log2(n).clamp(1, 3) as usize, distinct[0]),
+        };
+
+        for i in 0..N {
+            // SAFETY: i is in bounds due to the loop bound.
+            let v = unsafe { self.get_unchecked(i) };
+            optimized.set(i, v);
+        }
+
+        *self = optimized;
+    }
+
+    /// Returns the number of live distinct values currently held in the palette.
+    ///
+    /// Entries whose last occurrence has been overwritten are reclaimed rather than
+    /// counted here, so a workload that churns through many transient values while
+    /// keeping few live ones doesn't needlessly escalate the palette tier.
+    pub fn entry_count(&self) -> usize {
+        match &self.palette {
+            BiomePalette::SingleValue(_) => 1,
+            BiomePalette::Linear { palette, .. } => palette.entry_count(),
+        }
+    }
+
+    /// Returns an iterator over every decoded value in index order.
+    ///
+    /// The palette variant is resolved once up front, so each step of iteration only
+    /// does a `PackedBits` read and a slice index rather than re-matching the palette
+    /// enum and re-checking bounds per element like a loop of [`Self::get`] would.
+    pub fn iter(&self) -> BiomePaletteIter<'_, N> {
+        let inner = match &self.palette {
+            BiomePalette::SingleValue(v) => BiomePaletteIterInner::SingleValue(v.0),
+            BiomePalette::Linear { palette, data } => {
+                BiomePaletteIterInner::Linear { palette, data }
+            }
+        };
+        BiomePaletteIter { inner, pos: 0 }
+    }
+
+    /// Bulk-copies every decoded value into `dst` in index order.
+    ///
+    /// # Panics
+    /// Panics if `dst` is shorter than `N`.
+    pub fn copy_into(&self, dst: &mut [u64]) {
+        assert!(dst.len() >= N, "dst is too short to hold all entries");
+        for (slot, v) in dst.iter_mut().zip(self.iter()) {
+            *slot = v;
+        }
     }
 }
 
+enum BiomePaletteIterInner<'a, const N: usize> {
+    SingleValue(u64),
+    Linear {
+        palette: &'a LinearPalette,
+        data: &'a PackedBits<N>,
+    },
+}
+
+/// Iterator over the decoded values of a [`BiomePaletteContainer`], yielded in index
+/// order. See [`BiomePaletteContainer::iter`].
+pub struct BiomePaletteIter<'a, const N: usize> {
+    inner: BiomePaletteIterInner<'a, N>,
+    pos: usize,
+}
+
+impl<const N: usize> Iterator for BiomePaletteIter<'_, N> {
+    type Item = u64;
+
+    fn next(&mut self) -> Option<u64> {
+        if self.pos >= N {
+            return None;
+        }
+        let i = self.pos;
+        self.pos += 1;
+        Some(match &self.inner {
+            BiomePaletteIterInner::SingleValue(v) => *v,
+            // SAFETY: i < N was just checked above.
+            BiomePaletteIterInner::Linear { palette, data } => {
+                palette.value(unsafe { data.get_unchecked(i) } as usize)
+            }
+        })
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remaining = N - self.pos;
+        (remaining, Some(remaining))
+    }
+}
+
+impl<const N: usize> ExactSizeIterator for BiomePaletteIter<'_, N> {}
+
 pub struct StatePaletteContainer<const N: usize> {
     palette: StatePalette<N>,
 }
@@ -165,12 +366,28 @@ impl<const N: usize> StatePaletteContainer<N> {
     }
 
     pub fn with_bits(bits: usize, value: u64) -> Self {
-        match bits {
+        Self::try_with_bits(bits, value).expect("allocation failure")
+    }
+
+    /// Fallible counterpart to [`Self::with_bits`].
+    pub fn try_with_bits(bits: usize, value: u64) -> Result<Self, TryReserveError> {
+        Ok(match bits {
             0 => Self::new(value),
             1..=4 => {
                 let mut values = Vec::new();
-                values.reserve_exact(2usize.pow(4));
-                let palette = LinearPalette { bits: 4, values };
+                values.try_reserve_exact(2usize.pow(4))?;
+                values.push(value);
+                // `data` is freshly zeroed, so every cell's raw index is 0 until a
+                // caller's first `set()` overwrites it; seeding `values`/`counts` as if
+                // all N cells already held `value` at index 0 keeps that first `set()`
+                // (which reads the stale `old = 0` and calls `release(0)`) from
+                // double-subtracting an entry that was never really there.
+                let counts = vec![N as u32];
+                let palette = LinearPalette {
+                    bits: 4,
+                    values,
+                    counts,
+                };
                 Self {
                     palette: StatePalette::Linear {
                         palette,
@@ -180,10 +397,18 @@ impl<const N: usize> StatePaletteContainer<N> {
             }
             5..=8 => {
                 let mut values = Vec::new();
-                values.reserve_exact(2usize.pow(bits as u32));
-                let palette = LinearPalette { bits, values };
+                values.try_reserve_exact(2usize.pow(bits as u32))?;
+                values.push(value);
+                // See the `1..=4` arm above for why `counts` is seeded to `N` rather
+                // than left empty.
+                let counts = vec![N as u32];
+                let palette = LinearPalette {
+                    bits,
+                    values,
+                    counts,
+                };
                 let palette = MappedPalette {
-                    indices: BTreeMap::new(),
+                    indices: BTreeMap::from([(value, 0)]),
                     inner: palette,
                 };
                 Self {
@@ -195,15 +420,15 @@ impl<const N: usize> StatePaletteContainer<N> {
             }
             _ => Self {
                 palette: StatePalette::Global {
-                    data: PackedBits::new(bits),
+                    data: PackedBits::try_new(bits)?,
                 },
             },
-        }
+        })
     }
 }
 
 impl<const N: usize> StatePaletteContainer<N> {
-    pub fn get(&mut self, i: usize) -> u64 {
+    pub fn get(&self, i: usize) -> u64 {
         if i >= N {
             panic!("out of bounds")
         }
@@ -213,8 +438,9 @@ impl<const N: usize> StatePaletteContainer<N> {
 
     /// # Safety
     /// This method is safe as long as `i` is within bounds.
-    pub unsafe fn get_unchecked(&mut self, i: usize) -> u64 {
-        match &mut self.palette {
+    pub unsafe fn get_unchecked(&self, i: usize) -> u64 {
+        precondition!(i < N, "i ({i}) must be < N ({N})");
+        match &self.palette {
             StatePalette::SingleValue(v) => v.0,
             StatePalette::Linear { palette, data } => palette.value(data.get_unchecked(i) as usize),
             StatePalette::Mapped { palette, data } => palette.value(data.get_unchecked(i) as usize),
@@ -223,109 +449,510 @@ impl<const N: usize> StatePaletteContainer<N> {
     }
 
     pub fn set(&mut self, i: usize, v: u64) {
+        self.try_set(i, v).expect("allocation failure")
+    }
+
+    /// Fallible counterpart to [`Self::set`] that rejects the write instead of aborting
+    /// the process when growing the backing storage fails, e.g. because an attacker-
+    /// controlled chunk tries to force the palette all the way up to the `Global` tier.
+    pub fn try_set(&mut self, i: usize, v: u64) -> Result<(), TryReserveError> {
         if i >= N {
             panic!("out of bounds")
         }
         // SAFETY: This is sound because we just checked the bounds
-        unsafe { self.set_unchecked(i, v) }
+        unsafe { self.try_set_unchecked(i, v) }
     }
 
     /// # Safety
     /// This method is safe as long as `i` is within bounds.
     pub unsafe fn set_unchecked(&mut self, i: usize, v: u64) {
+        self.try_set_unchecked(i, v).expect("allocation failure")
+    }
+
+    /// Fallible counterpart to [`Self::set_unchecked`].
+    ///
+    /// # Safety
+    /// This method is safe as long as `i` is within bounds.
+    pub unsafe fn try_set_unchecked(&mut self, i: usize, v: u64) -> Result<(), TryReserveError> {
+        precondition!(i < N, "i ({i}) must be < N ({N})");
         loop {
             match &mut self.palette {
                 StatePalette::SingleValue(val) => match val.index(v) {
-                    IndexOrBits::Index(_) => return,
+                    IndexOrBits::Index(_) => return Ok(()),
                     IndexOrBits::Bits(_) => {
                         let mut values = Vec::new();
-                        values.reserve_exact(2usize.pow(4));
+                        values.try_reserve_exact(2usize.pow(4))?;
                         values.push(val.0);
+                        // All N cells are still implicitly holding `val.0` at this point;
+                        // the loop re-enters the `Linear` arm below for the same `i`,
+                        // which writes its new index and calls `palette.release(old)`
+                        // (reading the fresh, all-zero `data` as `old == 0`) to drop this
+                        // count by one. Seeding it to `N` rather than `N - 1` accounts for
+                        // that still-pending release instead of double-subtracting `i`.
+                        let counts = vec![N as u32];
                         let palette = StatePalette::Linear {
-                            palette: LinearPalette { bits: 4, values },
+                            palette: LinearPalette {
+                                bits: 4,
+                                values,
+                                counts,
+                            },
                             data: PackedBits::new(4),
                         };
                         self.palette = palette;
                     }
                 },
-                StatePalette::Linear { palette, data } => match palette.index(v) {
-                    IndexOrBits::Index(v) => return data.set(i, v),
-                    IndexOrBits::Bits(bits) => {
-                        debug_assert_eq!(bits, 5);
-                        // We know bits will always be 5
-                        data.change_bits(bits);
-                        let data = std::mem::take(data);
-                        let mut values = std::mem::take(&mut palette.values);
-                        // Here we double the capacity so that it is equal to 2 to the power of 5
-                        values.reserve_exact(2usize.pow(4)); // values.capacity() should be equal to 2usize.pow(4)
-                        let palette = StatePalette::Mapped {
-                            palette: MappedPalette {
-                                indices: BTreeMap::new(),
-                                inner: LinearPalette { values, bits: 5 },
-                            },
-                            data,
-                        };
+                StatePalette::Linear { palette, data } => {
+                    let old = data.get(i);
+                    match palette.try_index(v)? {
+                        IndexOrBits::Index(new_index) => {
+                            data.set(i, new_index);
+                            palette.release(old as usize);
+                            return Ok(());
+                        }
+                        IndexOrBits::Bits(bits) => {
+                            debug_assert_eq!(bits, 5);
+                            // We know bits will always be 5
+                            data.try_change_bits(bits)?;
+                            let data = std::mem::take(data);
+                            let mut values = std::mem::take(&mut palette.values);
+                            let counts = std::mem::take(&mut palette.counts);
+                            // Here we double the capacity so that it is equal to 2 to the power of 5
+                            values.try_reserve_exact(2usize.pow(4))?; // values.capacity() should be equal to 2usize.pow(4)
+                            let palette = StatePalette::Mapped {
+                                palette: MappedPalette {
+                                    indices: BTreeMap::new(),
+                                    inner: LinearPalette {
+                                        values,
+                                        bits: 5,
+                                        counts,
+                                    },
+                                },
+                                data,
+                            };
 
-                        self.palette = palette;
+                            self.palette = palette;
+                        }
                     }
-                },
-                StatePalette::Mapped { palette, data } => match palette.index(v) {
-                    IndexOrBits::Index(v) => return data.set_unchecked(i, v),
-                    IndexOrBits::Bits(bits) => {
-                        let palette: StatePalette<N> = if bits == 9 {
-                            let mut new_data = PackedBits::new(15);
-                            for i in 0..N {
-                                //SAFETY: This is fine because the for loop makes sure `i` stays in bounds
-                                new_data.set_unchecked(i, self.get_unchecked(i));
-                            }
+                }
+                StatePalette::Mapped { palette, data } => {
+                    let old = data.get_unchecked(i);
+                    match palette.try_index(v)? {
+                        IndexOrBits::Index(new_index) => {
+                            data.set_unchecked(i, new_index);
+                            palette.release(old as usize);
+                            return Ok(());
+                        }
+                        IndexOrBits::Bits(bits) => {
+                            let palette: StatePalette<N> = if bits == 9 {
+                                let mut new_data = PackedBits::try_new(15)?;
+                                for i in 0..N {
+                                    //SAFETY: This is fine because the for loop makes sure `i` stays in bounds
+                                    new_data.set_unchecked(i, self.get_unchecked(i));
+                                }
 
-                            StatePalette::Global { data: new_data }
-                        } else {
-                            data.change_bits(bits);
-                            let data = std::mem::take(data);
+                                StatePalette::Global { data: new_data }
+                            } else {
+                                data.try_change_bits(bits)?;
+                                let data = std::mem::take(data);
 
-                            let linear = LinearPalette {
-                                values: std::mem::take(&mut palette.inner.values),
-                                bits,
+                                let linear = LinearPalette {
+                                    values: std::mem::take(&mut palette.inner.values),
+                                    bits,
+                                    counts: std::mem::take(&mut palette.inner.counts),
+                                };
+                                StatePalette::Mapped {
+                                    palette: MappedPalette {
+                                        indices: std::mem::take(&mut palette.indices),
+                                        inner: linear,
+                                    },
+                                    data,
+                                }
                             };
-                            StatePalette::Mapped {
-                                palette: MappedPalette {
-                                    indices: std::mem::take(&mut palette.indices),
-                                    inner: linear,
-                                },
-                                data,
-                            }
-                        };
-                        self.palette = palette;
+                            self.palette = palette;
+                        }
                     }
-                },
-                StatePalette::Global { data } => return data.set_unchecked(i, v.into()),
+                }
+                StatePalette::Global { data } => return Ok(data.set_unchecked(i, v.into())),
             }
         }
     }
 
     pub fn swap(&mut self, i: usize, v: u64) -> u64 {
+        self.try_swap(i, v).expect("allocation failure")
+    }
+
+    /// Fallible counterpart to [`Self::swap`].
+    pub fn try_swap(&mut self, i: usize, v: u64) -> Result<u64, TryReserveError> {
         if i >= N {
             panic!("out of bounds")
         }
         //SAFETY: This is safe because we just checked the bounds.
-        unsafe { self.swap_unchecked(i, v) }
+        unsafe { self.try_swap_unchecked(i, v) }
     }
 
     /// # Safety
     /// This method is safe as long as `i` is within bounds
     pub unsafe fn swap_unchecked(&mut self, i: usize, v: u64) -> u64 {
+        self.try_swap_unchecked(i, v).expect("allocation failure")
+    }
+
+    /// Fallible counterpart to [`Self::swap_unchecked`].
+    ///
+    /// # Safety
+    /// This method is safe as long as `i` is within bounds
+    pub unsafe fn try_swap_unchecked(
+        &mut self,
+        i: usize,
+        v: u64,
+    ) -> Result<u64, TryReserveError> {
         let val = self.get_unchecked(i);
-        self.set_unchecked(i, v);
-        val
+        self.try_set_unchecked(i, v)?;
+        Ok(val)
+    }
+
+    /// Recomputes the minimal palette tier needed to represent the current contents
+    /// and repacks the data accordingly, downgrading the tier if writes have since
+    /// left only a handful of distinct values live. `get(i)` returns the same values
+    /// before and after this call.
+    pub fn optimize(&mut self) {
+        let mut distinct: Vec<u64> = Vec::new();
+        for i in 0..N {
+            // SAFETY: i is in bounds due to the loop bound.
+            let v = unsafe { self.get_unchecked(i) };
+            if !distinct.contains(&v) {
+                distinct.push(v);
+            }
+        }
+
+        let mut optimized = match distinct.len() {
+            0 | 1 => Self::new(distinct.first().copied().unwrap_or(0)),
+            n if n <= 16 => Self::with_bits(4, distinct[0]),
+            n if n <= 256 => Self::with_bits(ceil_log2(n).clamp(5, 8) as usize, distinct[0]),
+            // Above the `Mapped` threshold we fall back to the same fixed global bit
+            // width `try_set_unchecked` uses when promoting out of `Mapped`.
+            _ => Self::with_bits(15, distinct[0]),
+        };
+
+        for i in 0..N {
+            // SAFETY: i is in bounds due to the loop bound.
+            let v = unsafe { self.get_unchecked(i) };
+            optimized.set(i, v);
+        }
+
+        *self = optimized;
+    }
+
+    /// Returns the number of live distinct values currently held in the palette.
+    ///
+    /// Entries whose last occurrence has been overwritten are reclaimed rather than
+    /// counted here, so a workload that churns through many transient values while
+    /// keeping few live ones doesn't needlessly escalate the palette tier.
+    pub fn entry_count(&self) -> usize {
+        match &self.palette {
+            StatePalette::SingleValue(_) => 1,
+            StatePalette::Linear { palette, .. } => palette.entry_count(),
+            StatePalette::Mapped { palette, .. } => palette.entry_count(),
+            StatePalette::Global { .. } => {
+                let mut distinct: Vec<u64> = Vec::new();
+                for v in self.iter() {
+                    if !distinct.contains(&v) {
+                        distinct.push(v);
+                    }
+                }
+                distinct.len()
+            }
+        }
+    }
+
+    /// Returns an iterator over every decoded value in index order.
+    ///
+    /// The palette variant is resolved once up front, so each step of iteration only
+    /// does a `PackedBits` read and a slice index rather than re-matching the palette
+    /// enum and re-checking bounds per element like a loop of [`Self::get`] would.
+    pub fn iter(&self) -> StatePaletteIter<'_, N> {
+        let inner = match &self.palette {
+            StatePalette::SingleValue(v) => StatePaletteIterInner::SingleValue(v.0),
+            StatePalette::Linear { palette, data } => {
+                StatePaletteIterInner::Linear { palette, data }
+            }
+            StatePalette::Mapped { palette, data } => {
+                StatePaletteIterInner::Mapped { palette, data }
+            }
+            StatePalette::Global { data } => StatePaletteIterInner::Global { data },
+        };
+        StatePaletteIter { inner, pos: 0 }
+    }
+
+    /// Bulk-copies every decoded value into `dst` in index order.
+    ///
+    /// # Panics
+    /// Panics if `dst` is shorter than `N`.
+    pub fn copy_into(&self, dst: &mut [u64]) {
+        assert!(dst.len() >= N, "dst is too short to hold all entries");
+        for (slot, v) in dst.iter_mut().zip(self.iter()) {
+            *slot = v;
+        }
+    }
+}
+
+enum StatePaletteIterInner<'a, const N: usize> {
+    SingleValue(u64),
+    Linear {
+        palette: &'a LinearPalette,
+        data: &'a PackedBits<N>,
+    },
+    Mapped {
+        palette: &'a MappedPalette,
+        data: &'a PackedBits<N>,
+    },
+    Global {
+        data: &'a PackedBits<N>,
+    },
+}
+
+/// Iterator over the decoded values of a [`StatePaletteContainer`], yielded in index
+/// order. See [`StatePaletteContainer::iter`].
+pub struct StatePaletteIter<'a, const N: usize> {
+    inner: StatePaletteIterInner<'a, N>,
+    pos: usize,
+}
+
+impl<const N: usize> Iterator for StatePaletteIter<'_, N> {
+    type Item = u64;
+
+    fn next(&mut self) -> Option<u64> {
+        if self.pos >= N {
+            return None;
+        }
+        let i = self.pos;
+        self.pos += 1;
+        Some(match &self.inner {
+            StatePaletteIterInner::SingleValue(v) => *v,
+            // SAFETY: i < N was just checked above.
+            StatePaletteIterInner::Linear { palette, data } => {
+                palette.value(unsafe { data.get_unchecked(i) } as usize)
+            }
+            // SAFETY: i < N was just checked above.
+            StatePaletteIterInner::Mapped { palette, data } => {
+                palette.value(unsafe { data.get_unchecked(i) } as usize)
+            }
+            // SAFETY: i < N was just checked above.
+            StatePaletteIterInner::Global { data } => u64::from(unsafe { data.get_unchecked(i) }),
+        })
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remaining = N - self.pos;
+        (remaining, Some(remaining))
+    }
+}
+
+impl<const N: usize> ExactSizeIterator for StatePaletteIter<'_, N> {}
+
+/// Tallies how many of a freshly-decoded [`PackedBits`]'s `N` entries point at each of
+/// the `palette_len` palette slots, so a container built straight from wire data starts
+/// with accurate occupancy counts for [`LinearPalette::release`]/[`MappedPalette::release`].
+fn occupancy_counts<const N: usize>(data: &PackedBits<N>, palette_len: usize) -> Vec<u32> {
+    let mut counts = vec![0u32; palette_len];
+    for i in 0..N {
+        // SAFETY: i is in bounds due to the loop bound.
+        let index = unsafe { data.get_unchecked(i) } as usize;
+        if let Some(count) = counts.get_mut(index) {
+            *count += 1;
+        }
+    }
+    counts
+}
+
+impl<const N: usize> Encode for BiomePaletteContainer<N> {
+    fn encode(&self, writer: &mut impl std::io::Write) -> miners::encoding::encode::Result<()> {
+        match &self.palette {
+            BiomePalette::SingleValue(v) => {
+                0u8.encode(writer)?;
+                VarInt(v.0 as i32).encode(writer)?;
+                LenPrefixed::<VarInt, u64>(Vec::new()).encode(writer)
+            }
+            BiomePalette::Linear { palette, data } => {
+                (palette.bits as u8).encode(writer)?;
+                VarInt(palette.values.len() as i32).encode(writer)?;
+                for value in &palette.values {
+                    VarInt(*value as i32).encode(writer)?;
+                }
+                data.encode(writer)
+            }
+        }
+    }
+}
+
+impl<'dec, const N: usize> Decode<'dec> for BiomePaletteContainer<N> {
+    fn decode(cursor: &mut std::io::Cursor<&'dec [u8]>) -> miners::encoding::decode::Result<Self> {
+        let bits = u8::decode(cursor)? as usize;
+        Ok(match bits {
+            0 => {
+                let value = VarInt::decode(cursor)?.0 as u64;
+                // The single-valued layout still encodes an (empty) packed long array.
+                LenPrefixed::<VarInt, u64>::decode(cursor)?;
+                Self::new(value)
+            }
+            bits if bits > 3 => {
+                // There's no Direct/Global tier for biomes (unlike block states):
+                // `BiomePalette` only ever has a `SingleValue`/`Linear` arm, so
+                // `bits <= 3` is an invariant every other biome code path enforces via
+                // `precondition!`. Accepting a larger `bits` here would construct a
+                // `BiomePaletteContainer` that already violates it, and the first
+                // subsequent `set`/`try_set` that needs to grow the palette would hit
+                // the `bits > 3` panic instead of a graceful error.
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::InvalidData,
+                    format!("biome palette bits ({bits}) must not exceed 3"),
+                )
+                .into());
+            }
+            bits => {
+                let len = VarInt::decode(cursor)?.0 as usize;
+                let mut values = Vec::with_capacity(len);
+                for _ in 0..len {
+                    values.push(VarInt::decode(cursor)?.0 as u64);
+                }
+                let data = PackedBits::decode_with_bits(bits, cursor)?;
+                let counts = occupancy_counts(&data, values.len());
+                Self {
+                    palette: BiomePalette::Linear {
+                        palette: LinearPalette { bits, values, counts },
+                        data,
+                    },
+                }
+            }
+        })
+    }
+}
+
+impl<const N: usize> Encode for StatePaletteContainer<N> {
+    fn encode(&self, writer: &mut impl std::io::Write) -> miners::encoding::encode::Result<()> {
+        match &self.palette {
+            StatePalette::SingleValue(v) => {
+                0u8.encode(writer)?;
+                VarInt(v.0 as i32).encode(writer)?;
+                LenPrefixed::<VarInt, u64>(Vec::new()).encode(writer)
+            }
+            StatePalette::Linear { palette, data } => {
+                (palette.bits as u8).encode(writer)?;
+                VarInt(palette.values.len() as i32).encode(writer)?;
+                for value in &palette.values {
+                    VarInt(*value as i32).encode(writer)?;
+                }
+                data.encode(writer)
+            }
+            StatePalette::Mapped { palette, data } => {
+                (palette.inner.bits as u8).encode(writer)?;
+                VarInt(palette.inner.values.len() as i32).encode(writer)?;
+                for value in &palette.inner.values {
+                    VarInt(*value as i32).encode(writer)?;
+                }
+                data.encode(writer)
+            }
+            // Direct storage: no palette section, just the packed global ids.
+            StatePalette::Global { data } => {
+                (data.bits() as u8).encode(writer)?;
+                data.encode(writer)
+            }
+        }
+    }
+}
+
+impl<'dec, const N: usize> Decode<'dec> for StatePaletteContainer<N> {
+    fn decode(cursor: &mut std::io::Cursor<&'dec [u8]>) -> miners::encoding::decode::Result<Self> {
+        let bits = u8::decode(cursor)? as usize;
+        Ok(match bits {
+            0 => {
+                let value = VarInt::decode(cursor)?.0 as u64;
+                // The single-valued layout still encodes an (empty) packed long array.
+                LenPrefixed::<VarInt, u64>::decode(cursor)?;
+                Self::new(value)
+            }
+            // Indirect, linear palette: a plain Vec lookup outperforms a BTreeMap below
+            // the threshold where Mapped's reverse index starts paying for itself.
+            1..=4 => {
+                let len = VarInt::decode(cursor)?.0 as usize;
+                let mut values = Vec::with_capacity(len);
+                for _ in 0..len {
+                    values.push(VarInt::decode(cursor)?.0 as u64);
+                }
+                let data = PackedBits::decode_with_bits(4, cursor)?;
+                let counts = occupancy_counts(&data, values.len());
+                Self {
+                    palette: StatePalette::Linear {
+                        palette: LinearPalette {
+                            bits: 4,
+                            values,
+                            counts,
+                        },
+                        data,
+                    },
+                }
+            }
+            // Indirect, mapped palette: the same layout, but backed by a reverse
+            // BTreeMap for O(log n) lookups once the palette is large enough.
+            5..=8 => {
+                let len = VarInt::decode(cursor)?.0 as usize;
+                let mut values = Vec::with_capacity(len);
+                for _ in 0..len {
+                    values.push(VarInt::decode(cursor)?.0 as u64);
+                }
+                let data = PackedBits::decode_with_bits(bits, cursor)?;
+                let counts = occupancy_counts(&data, values.len());
+                let indices = values
+                    .iter()
+                    .enumerate()
+                    .map(|(index, value)| (*value, index))
+                    .collect();
+                Self {
+                    palette: StatePalette::Mapped {
+                        palette: MappedPalette {
+                            indices,
+                            inner: LinearPalette {
+                                bits,
+                                values,
+                                counts,
+                            },
+                        },
+                        data,
+                    },
+                }
+            }
+            // Direct storage: global ids are packed with no palette indirection at all.
+            bits => {
+                let data = PackedBits::decode_with_bits(bits, cursor)?;
+                Self {
+                    palette: StatePalette::Global { data },
+                }
+            }
+        })
     }
 }
 
 trait Palette {
-    fn index(&mut self, value: u64) -> IndexOrBits;
+    /// Fallible counterpart to [`Self::index`]; growing the backing storage can fail
+    /// instead of aborting the process, e.g. under adversarial/OOM conditions.
+    fn try_index(&mut self, value: u64) -> Result<IndexOrBits, TryReserveError>;
+
+    fn index(&mut self, value: u64) -> IndexOrBits {
+        self.try_index(value).expect("allocation failure")
+    }
+
     fn value(&self, index: usize) -> u64;
 }
 
+/// The number of bits needed to represent `n` distinct values, i.e. `ceil(log2(n))`.
+#[inline]
+fn ceil_log2(n: usize) -> u32 {
+    if n <= 1 {
+        0
+    } else {
+        usize::BITS - (n - 1).leading_zeros()
+    }
+}
+
 // TODO: Rename?
 enum IndexOrBits {
     Index(u64),
@@ -336,12 +963,12 @@ enum IndexOrBits {
 struct SingleValuePalette(u64);
 
 impl Palette for SingleValuePalette {
-    fn index(&mut self, state: u64) -> IndexOrBits {
-        if self.0 == state {
+    fn try_index(&mut self, state: u64) -> Result<IndexOrBits, TryReserveError> {
+        Ok(if self.0 == state {
             IndexOrBits::Index(0)
         } else {
             IndexOrBits::Bits(1)
-        }
+        })
     }
 
     fn value(&self, index: usize) -> u64 {
@@ -356,27 +983,61 @@ impl Palette for SingleValuePalette {
 struct LinearPalette {
     pub(crate) values: Vec<u64>,
     pub(crate) bits: usize,
+    /// Occurrence count of each entry in `values`, kept in lockstep with it. A count
+    /// reaching zero means the value has no live occupants and the slot can be
+    /// reclaimed by a later, different value instead of growing the palette.
+    pub(crate) counts: Vec<u32>,
+}
+
+impl LinearPalette {
+    /// Number of entries with a non-zero occupancy.
+    fn entry_count(&self) -> usize {
+        self.counts.iter().filter(|&&c| c > 0).count()
+    }
+
+    /// Decrements the occurrence count of the entry at `index`, making the slot
+    /// reclaimable once it reaches zero.
+    fn release(&mut self, index: usize) {
+        if let Some(count) = self.counts.get_mut(index) {
+            debug_assert!(*count > 0, "released an already-unoccupied palette slot");
+            *count -= 1;
+        }
+    }
 }
 
 impl Palette for LinearPalette {
-    fn index(&mut self, state: u64) -> IndexOrBits {
+    fn try_index(&mut self, state: u64) -> Result<IndexOrBits, TryReserveError> {
         for i in 0..self.values.len() {
             // SAFETY: This is fine because i can only be in bounds due to the for loop.
             unsafe {
                 if *self.values.get_unchecked(i) == state {
-                    return IndexOrBits::Index(i as u64);
+                    *self.counts.get_unchecked_mut(i) += 1;
+                    return Ok(IndexOrBits::Index(i as u64));
+                }
+            }
+        }
+
+        // Reuse a slot whose last occupant was fully overwritten instead of growing.
+        for i in 0..self.values.len() {
+            // SAFETY: This is fine because i can only be in bounds due to the for loop.
+            unsafe {
+                if *self.counts.get_unchecked(i) == 0 {
+                    *self.values.get_unchecked_mut(i) = state;
+                    *self.counts.get_unchecked_mut(i) = 1;
+                    return Ok(IndexOrBits::Index(i as u64));
                 }
             }
         }
 
         let len = self.values.len();
-        if self.values.capacity() > len {
+        Ok(if self.values.capacity() > len {
             debug_assert_eq!(self.values.capacity(), 2usize.pow(self.bits as u32));
             self.values.push(state);
+            self.counts.push(1);
             IndexOrBits::Index(len as u64)
         } else {
             IndexOrBits::Bits(self.bits + 1)
-        }
+        })
     }
 
     #[inline]
@@ -391,25 +1052,55 @@ struct MappedPalette {
     pub(crate) inner: LinearPalette,
 }
 
+impl MappedPalette {
+    fn entry_count(&self) -> usize {
+        self.inner.entry_count()
+    }
+
+    /// Decrements the occurrence count of the entry at `index`, dropping its reverse
+    /// lookup entry once the slot becomes reclaimable so `indices` never points a
+    /// value at a slot that no longer holds it.
+    fn release(&mut self, index: usize) {
+        self.inner.release(index);
+        if self.inner.counts.get(index) == Some(&0) {
+            let value = self.inner.values[index];
+            if self.indices.get(&value) == Some(&index) {
+                self.indices.remove(&value);
+            }
+        }
+    }
+}
+
 impl Palette for MappedPalette {
-    fn index(&mut self, state: u64) -> IndexOrBits {
-        match self.indices.get(&state) {
-            Some(v) => IndexOrBits::Index(*v as u64),
-            None => {
-                let initial_len = self.inner.values.len();
-                if self.inner.values.capacity() > initial_len {
-                    debug_assert_eq!(
-                        self.inner.values.capacity(),
-                        2usize.pow(self.inner.bits as u32)
-                    );
-                    self.inner.values.push(state);
-                    self.indices.insert(state, self.inner.values.len());
-                    IndexOrBits::Index(initial_len as u64)
-                } else {
-                    IndexOrBits::Bits(self.inner.bits + 1)
-                }
+    fn try_index(&mut self, state: u64) -> Result<IndexOrBits, TryReserveError> {
+        if let Some(&index) = self.indices.get(&state) {
+            self.inner.counts[index] += 1;
+            return Ok(IndexOrBits::Index(index as u64));
+        }
+
+        // Reuse a slot whose last occupant was fully overwritten instead of growing.
+        for i in 0..self.inner.values.len() {
+            if self.inner.counts[i] == 0 {
+                self.inner.values[i] = state;
+                self.inner.counts[i] = 1;
+                self.indices.insert(state, i);
+                return Ok(IndexOrBits::Index(i as u64));
             }
         }
+
+        let initial_len = self.inner.values.len();
+        Ok(if self.inner.values.capacity() > initial_len {
+            debug_assert_eq!(
+                self.inner.values.capacity(),
+                2usize.pow(self.inner.bits as u32)
+            );
+            self.inner.values.push(state);
+            self.inner.counts.push(1);
+            self.indices.insert(state, initial_len);
+            IndexOrBits::Index(initial_len as u64)
+        } else {
+            IndexOrBits::Bits(self.inner.bits + 1)
+        })
     }
 
     fn value(&self, index: usize) -> u64 {
@@ -450,4 +1141,98 @@ mod tests {
             }
         }
     }
+
+    #[test]
+    fn optimize_collapses_to_single_value() {
+        let mut container = StatePaletteContainer::<512>::new(0);
+        for i in 0..512 {
+            container.set(i, 7);
+        }
+        container.optimize();
+        assert!(matches!(
+            container.palette,
+            super::StatePalette::SingleValue(_)
+        ));
+        for i in 0..512 {
+            assert_eq!(container.get(i), 7);
+        }
+    }
+
+    #[test]
+    fn optimize_preserves_values() {
+        let mut container = StatePaletteContainer::<512>::new(0);
+        for i in 0..512 {
+            container.set(i, (i % 3) as u64);
+        }
+        container.optimize();
+        for i in 0..512 {
+            assert_eq!(container.get(i), (i % 3) as u64);
+        }
+    }
+
+    #[test]
+    fn iter_matches_get() {
+        let mut container = StatePaletteContainer::<512>::new(0);
+        for i in 0..512 {
+            container.set(i, (i % 5) as u64);
+        }
+        let collected: Vec<u64> = container.iter().collect();
+        for i in 0..512 {
+            assert_eq!(collected[i], container.get(i));
+        }
+    }
+
+    #[test]
+    fn copy_into_matches_get() {
+        let mut container = BiomePaletteContainer::<8>::new(0);
+        for i in 0..8 {
+            container.set(i, (7 - i) as u64);
+        }
+        let mut dst = [0u64; 8];
+        container.copy_into(&mut dst);
+        for i in 0..8 {
+            assert_eq!(dst[i], container.get(i));
+        }
+    }
+
+    #[test]
+    fn entry_count_shrinks_as_values_are_overwritten() {
+        let mut container = BiomePaletteContainer::<8>::new(0);
+        for i in 0..8 {
+            container.set(i, i as u64);
+        }
+        assert_eq!(container.entry_count(), 8);
+
+        // Collapse every cell down to a single value; the other 7 entries should
+        // become reclaimable rather than staying "live".
+        for i in 0..8 {
+            container.set(i, 0);
+        }
+        assert_eq!(container.entry_count(), 1);
+        for i in 0..8 {
+            assert_eq!(container.get(i), 0);
+        }
+    }
+
+    #[test]
+    fn churning_values_reuses_reclaimed_slots() {
+        let mut container = StatePaletteContainer::<512>::new(0);
+        // Repeatedly overwrite the same cell with a long run of transient values,
+        // then settle on a handful of live ones; the palette shouldn't have needed
+        // to escalate tiers to accommodate all of the transient churn.
+        for v in 1..=100u64 {
+            container.set(0, v);
+        }
+        for i in 0..512 {
+            container.set(i, (i % 3) as u64);
+        }
+        assert_eq!(container.entry_count(), 3);
+        assert!(matches!(
+            container.palette,
+            super::StatePalette::Linear { .. }
+        ));
+        for i in 0..512 {
+            assert_eq!(container.get(i), (i % 3) as u64);
+        }
+    }
 }
@@ -0,0 +1,165 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::ptr::NonNull;
+
+use crate::chunk::ChunkColumn0;
+
+/// A previously-freed arena region, still allocated but not backing any live column.
+struct FreeBlock {
+    ptr: NonNull<u8>,
+    capacity: usize,
+}
+
+// SAFETY: `FreeBlock` just tracks an owned allocation; it isn't aliased while sitting
+// in a pool's free list, so it can be handed across threads along with the pool.
+unsafe impl Send for FreeBlock {}
+
+/// Pools the byte buffers backing [`ChunkColumn0`].
+///
+/// Without pooling, every [`ChunkColumn0::from_reader`] does a fresh `Vec::with_capacity`
+/// and every drop frees it, which thrashes the allocator when streaming many columns.
+/// Freed regions are instead kept in a free list keyed by [`size_class`], since section
+/// storage is always a multiple of 2048 bytes and therefore falls into a small number of
+/// classes. [`Self::recycle`] returns a column's buffer to the matching list instead of
+/// deallocating it, and [`Self::take`] (used by [`ChunkColumn0::reallocate`] and
+/// [`ChunkColumn0::from_reader`]) pulls from it before falling back to a fresh allocation.
+pub struct ChunkColumnPool {
+    free: HashMap<usize, Vec<FreeBlock>>,
+}
+
+/// Rounds `size` up to the pool's size class granularity, so buffers differing only in
+/// their exact byte count (e.g. one section with `sky_light`, one without) still land in
+/// a free list a later allocation of similar size can draw from.
+fn size_class(size: usize) -> usize {
+    size.next_multiple_of(2048).max(2048)
+}
+
+impl ChunkColumnPool {
+    pub fn new() -> Self {
+        Self {
+            free: HashMap::new(),
+        }
+    }
+
+    /// Returns a buffer with at least `size` bytes of capacity and the size class it was
+    /// rounded up to, reusing a recycled block if the matching free list has one.
+    pub(crate) fn take(&mut self, size: usize) -> (NonNull<u8>, usize) {
+        let class = size_class(size);
+        if let Some(block) = self.free.get_mut(&class).and_then(Vec::pop) {
+            return (block.ptr, block.capacity);
+        }
+        let mut vec = Vec::<u8>::with_capacity(class);
+        let ptr = vec.as_mut_ptr();
+        std::mem::forget(vec);
+        // SAFETY: `Vec::with_capacity` never hands back a null pointer.
+        (unsafe { NonNull::new_unchecked(ptr) }, class)
+    }
+
+    /// Returns `column`'s backing buffer to the free list instead of deallocating it, so
+    /// a later [`Self::take`] of the same size class can reuse it. The column is consumed
+    /// since it no longer owns a valid buffer afterwards.
+    pub fn recycle(&mut self, column: ChunkColumn0<'_>) {
+        let (buf, capacity) = column.into_raw_parts();
+        if let Some(buf) = buf {
+            self.recycle_raw(buf, capacity);
+        }
+    }
+
+    /// Returns a raw buffer directly to the free list, for callers (like
+    /// [`ChunkColumn0::reallocate`]) that swap a column onto a new buffer and need to
+    /// give up the old one without constructing a whole column around it first.
+    pub(crate) fn recycle_raw(&mut self, ptr: NonNull<u8>, capacity: usize) {
+        self.free
+            .entry(size_class(capacity))
+            .or_default()
+            .push(FreeBlock { ptr, capacity });
+    }
+}
+
+impl Default for ChunkColumnPool {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Drop for ChunkColumnPool {
+    fn drop(&mut self) {
+        for blocks in self.free.values() {
+            for block in blocks {
+                // SAFETY: every `FreeBlock` was produced by `Vec::with_capacity` (either
+                // directly in `take`, or indirectly via a recycled column's own buffer),
+                // and is removed from the free list before being reused, so this is the
+                // only place it's ever freed.
+                drop(unsafe {
+                    Vec::<u8>::from_raw_parts(block.ptr.as_ptr(), 0, block.capacity)
+                });
+            }
+        }
+    }
+}
+
+thread_local! {
+    /// Pool used by [`ChunkColumn0::from_reader`] and [`ChunkColumn0::reallocate`]. Neither
+    /// takes a pool argument, so both always draw buffers from this thread-local pool.
+    static DEFAULT_POOL: RefCell<ChunkColumnPool> = RefCell::new(ChunkColumnPool::new());
+}
+
+/// Runs `f` against the thread-local default pool.
+pub(crate) fn with_default_pool<R>(f: impl FnOnce(&mut ChunkColumnPool) -> R) -> R {
+    DEFAULT_POOL.with(|pool| f(&mut pool.borrow_mut()))
+}
+
+/// Returns `column` to the thread-local default pool.
+///
+/// This is the convenience counterpart to [`ChunkColumn0::from_reader`]: callers that
+/// don't manage their own [`ChunkColumnPool`] can still get buffer reuse by recycling
+/// through the default one.
+pub fn recycle(column: ChunkColumn0<'_>) {
+    with_default_pool(|pool| pool.recycle(column));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn take_reuses_a_recycled_block_of_the_same_size_class() {
+        let mut pool = ChunkColumnPool::new();
+        let (ptr, capacity) = pool.take(4096);
+        pool.recycle_raw(ptr, capacity);
+
+        let (ptr2, capacity2) = pool.take(4096);
+        assert_eq!(
+            ptr, ptr2,
+            "take should hand back the just-recycled block instead of allocating fresh"
+        );
+        assert_eq!(capacity, capacity2);
+        pool.recycle_raw(ptr2, capacity2);
+    }
+
+    #[test]
+    fn take_rounds_up_to_the_2048_byte_size_class() {
+        let mut pool = ChunkColumnPool::new();
+        let (ptr, capacity) = pool.take(1);
+        assert_eq!(capacity, 2048);
+        pool.recycle_raw(ptr, capacity);
+
+        let (ptr, capacity) = pool.take(2049);
+        assert_eq!(capacity, 4096);
+        pool.recycle_raw(ptr, capacity);
+    }
+
+    #[test]
+    fn blocks_from_different_size_classes_are_not_conflated() {
+        let mut pool = ChunkColumnPool::new();
+        let (small, small_capacity) = pool.take(2048);
+        pool.recycle_raw(small, small_capacity);
+
+        // No recycled block of this larger class exists yet, so this must allocate
+        // fresh rather than reusing `small`.
+        let (big, big_capacity) = pool.take(4096);
+        assert_ne!(small, big);
+
+        pool.recycle_raw(big, big_capacity);
+    }
+}